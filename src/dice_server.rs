@@ -1,28 +1,32 @@
+mod exporter;
+mod ingest;
+mod middleware;
+
 use lazy_static::lazy_static;
-use opentelemetry::metrics::Counter;
-use std::collections::HashMap;
-use std::str::FromStr;
+use opentelemetry::metrics::{Counter, Histogram};
 use std::sync::Arc;
 use std::time::Duration;
 
-use actix_web::{get, App, HttpRequest, HttpResponse, HttpServer, Responder};
-use awc::http::header::{HeaderMap, HeaderName, HeaderValue};
-use opentelemetry::metrics::MetricsError;
-use opentelemetry::trace::{SpanKind, TraceContextExt, TraceError, Tracer};
+use actix_web::{get, App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Responder};
+use opentelemetry::global;
+use opentelemetry::trace::TracerProvider as _;
 use opentelemetry::Context;
-use opentelemetry::{global, KeyValue};
-use opentelemetry_http::HeaderInjector;
-use opentelemetry_otlp::WithExportConfig;
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
 use opentelemetry_sdk::propagation::TraceContextPropagator;
 use opentelemetry_sdk::trace as sdktrace;
-use opentelemetry_sdk::{runtime, Resource};
-use opentelemetry_semantic_conventions::resource::SERVICE_NAME;
 use rand::Rng;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::prelude::*;
+
+use exporter::ExporterSettings;
+use middleware::{ClientTracingMiddleware, TracingMiddlewareFactory};
 
 // 定义一个结构体来保存我们的计数器
 struct HttpMetrics {
     success_counter: Counter<u64>,
     failure_counter: Counter<u64>,
+    duration_histogram: Histogram<f64>,
 }
 
 // 使用 lazy_static 创建一个全局的 HttpMetrics 实例
@@ -38,196 +42,194 @@ lazy_static! {
                 .u64_counter("http_requests_failure")
                 .with_description("失败的 HTTP 请求总数")
                 .init(),
+            duration_histogram: meter
+                .f64_histogram("http_request_duration_seconds")
+                .with_description("HTTP 请求耗时")
+                .with_unit(opentelemetry::metrics::Unit::new("s"))
+                .init(),
         }
     });
 }
 
-fn inject_context(request: &mut HeaderMap, cx: &Context) {
-    // 使用 OpenTelemetry 的 HTTP 传播器 (propagator) 注入追踪上下文到 HTTP 请求头
-
-    let mut r_headers = http::HeaderMap::new();
-
-    global::get_text_map_propagator(|propagator| {
-        propagator.inject_context(&cx, &mut HeaderInjector(&mut r_headers));
-    });
-
-    println!("randnum: r_headers: {:?}", &r_headers);
-
-    for (key, value) in r_headers.iter() {
-        let header_name = HeaderName::from_str(key.as_str()).unwrap();
-        let header_value = HeaderValue::from_str(value.to_str().unwrap()).unwrap();
-
-        request.insert(header_name, header_value);
+// 按 `http.route` / `http.response.status_code` 打标，记录一次请求的计数与耗时。
+// 由 `middleware::TracingMiddleware` 在每个请求完成后统一调用，而不是由各 handler 自行调用，
+// 这样新增的路由（比如 ingest::receive_traces）也能自动被计入，不会漏掉。
+pub(crate) fn record_request_metrics(route: &str, status: u16, elapsed: Duration) {
+    let attributes = [
+        KeyValue::new("http.route", route.to_string()),
+        KeyValue::new("http.response.status_code", status as i64),
+    ];
+
+    if status < 400 {
+        HTTP_METRICS.success_counter.add(1, &attributes);
+    } else {
+        HTTP_METRICS.failure_counter.add(1, &attributes);
     }
+    HTTP_METRICS
+        .duration_histogram
+        .record(elapsed.as_secs_f64(), &attributes);
 }
 
-fn extract_context(req: &HttpRequest) -> Context {
-    global::get_text_map_propagator(|propagator| {
-        let mut headers: HashMap<String, String> = HashMap::new();
-
-        for (key, value) in req.headers().iter() {
-            headers.insert(key.to_string(), value.to_str().unwrap().to_string());
-        }
-        propagator.extract(&headers)
-    })
-}
-
-fn get_cx_from_parent_cx<'a>(
-    tracer_name: String,
-    spam_name: String,
-    parent_cx: Option<&Context>,
-) -> Context {
-    let span;
-    let tracer = global::tracer(tracer_name);
-    match parent_cx {
-        Some(cx) => {
-            // 使用提取到的上下文作为父上下文，创建一个新的 span
-            span = tracer
-                .span_builder(spam_name)
-                .with_kind(SpanKind::Server)
-                .start_with_context(&tracer, cx);
-        }
-        None => {
-            span = tracer
-                .span_builder(spam_name)
-                .with_kind(SpanKind::Server)
-                .start(&tracer);
-        }
+// 把中间件存入 request extensions 的 OTel 上下文设置为当前 tracing span 的父 span，
+// 这样 `#[tracing::instrument]` 产生的 span 就能正确挂到请求的 Server span 下面。
+fn attach_parent_context(req: &HttpRequest) {
+    if let Some(cx) = req.extensions().get::<Context>() {
+        tracing::Span::current().set_parent(cx.clone());
     }
-
-    Context::current_with_span(span)
 }
 
 #[get("/randnum")]
-async fn randnum() -> impl Responder {
-    let cx = get_cx_from_parent_cx("dice_server".to_string(), "randnum".to_string(), None);
-
-    println!("randnum: 当前上下文: {:?}", cx);
-    println!("randnum: 当前 span: {:?}", cx.span());
+#[tracing::instrument(skip_all)]
+async fn randnum(req: HttpRequest) -> impl Responder {
+    attach_parent_context(&req);
 
-    let mut request = awc::Client::default().get("http://127.0.0.1:8080/gen_num");
+    let client = awc::Client::builder()
+        .wrap(ClientTracingMiddleware::new())
+        .finish();
 
-    let req_headers = request.headers_mut();
-    inject_context(req_headers, &cx);
-
-    match request.send().await {
+    let response = match client.get("http://127.0.0.1:8080/gen_num").send().await {
         Ok(mut response) => match response.body().await {
             Ok(body) => {
-                HTTP_METRICS.success_counter.add(1, &[]);
-                cx.span().add_event("从 gen_num 收到响应", vec![]);
+                tracing::info!("从 gen_num 收到响应");
                 HttpResponse::Ok().body(body)
             }
             Err(_) => {
-                HTTP_METRICS.failure_counter.add(1, &[]);
-                cx.span().add_event("读取响应体失败", vec![]);
+                tracing::warn!("读取响应体失败");
                 HttpResponse::InternalServerError().body("读取响应体失败")
             }
         },
         Err(_) => {
-            HTTP_METRICS.failure_counter.add(1, &[]);
-            cx.span().add_event("发送请求失败", vec![]);
+            tracing::warn!("发送请求失败");
             HttpResponse::InternalServerError().body("发送请求失败")
         }
-    }
+    };
+
+    response
 }
 
 #[get("/gen_num")]
+#[tracing::instrument(skip_all)]
 async fn gen_num(req: HttpRequest) -> impl Responder {
-    // 使用 OpenTelemetry 的 HTTP 传播器 (propagator) 从 HTTP 请求头中提取追踪上下文
-    let parent_cx = extract_context(&req);
-
-    println!("gen_num: parent_cx: {:?}", parent_cx);
-    println!("gen_num: parent_cx.span: {:?}", parent_cx.span());
-
-    let cx = get_cx_from_parent_cx(
-        "dice_server".to_string(),
-        "gen_num".to_string(),
-        Some(&parent_cx),
-    );
+    attach_parent_context(&req);
 
     let mut random_number: i32 = rand::thread_rng().gen_range(1..10);
     random_number *= 2;
 
-    // 生成奇数 or 偶数?
-    let is_odd = is_odd(&cx);
-    if is_odd {
+    // 生成奇数 or 偶数? is_odd 自身也是一个 span，会通过 tracing 的 span 栈自动挂到这里，
+    // 不需要再手动传递 &Context。
+    if is_odd() {
         random_number += 1;
     }
 
-    cx.span().add_event(
-        "Generated random number",
-        vec![opentelemetry::KeyValue::new(
-            "number",
-            random_number.to_string(),
-        )],
-    );
+    tracing::info!(number = random_number, "Generated random number");
 
-    HTTP_METRICS.success_counter.add(1, &[]);
     HttpResponse::Ok().body(random_number.to_string())
 }
 
-fn is_odd(cx: &Context) -> bool {
-    let cx = get_cx_from_parent_cx("dice_server".to_string(), "is_odd".to_string(), Some(cx));
-
+#[tracing::instrument]
+fn is_odd() -> bool {
     // 50% 的概率返回 true，50% 的概率返回 false
     let res = rand::thread_rng().gen_bool(0.5);
-    cx.span().add_event(
-        "odd or even",
-        vec![opentelemetry::KeyValue::new("is odd?", res.to_string())],
-    );
+    tracing::info!(is_odd = res, "odd or even");
     res
 }
 
-fn init_meter_provider() -> Result<opentelemetry_sdk::metrics::SdkMeterProvider, MetricsError> {
-    opentelemetry_otlp::new_pipeline()
-        .metrics(runtime::Tokio)
-        .with_period(Duration::from_secs(5))
-        .with_exporter(
-            opentelemetry_otlp::new_exporter()
-                .tonic() // 使用 Tonic 作为 gRPC 客户端
-                .with_endpoint("http://localhost:4317"), // TODO: 给出一个metric收集器的方案
-        )
-        .build()
-}
+// 初始化全局追踪器和指标提供者，并把 `tracing` 接入同一条 OTel pipeline。
+// 导出协议由 `ExporterSettings::from_env` 决定：未配置时使用 stdout 导出器，无需本地起 collector 即可跑通。
+// 返回两个 provider 的句柄（而不是只设置为全局后丢弃），以便进程退出前显式 flush。
+fn init_tracer(
+) -> Result<(sdktrace::TracerProvider, SdkMeterProvider), Box<dyn std::error::Error>> {
+    let settings = ExporterSettings::from_env();
 
-// 初始化追踪提供者 (Tracer Provider)，该函数返回一个全局的 `TracerProvider`
-fn init_tracer_provider() -> Result<opentelemetry_sdk::trace::TracerProvider, TraceError> {
-    opentelemetry_otlp::new_pipeline()
-        .tracing()
-        // 配置一个 OTLP 导出器，用于将追踪数据发送到指定的后端（在这里是 Jaeger 或 OpenTelemetry Collector）
-        .with_exporter(
-            opentelemetry_otlp::new_exporter()
-                .tonic() // 使用 Tonic 作为 gRPC 客户端
-                .with_endpoint("http://localhost:4317"), // 指定 OTLP 接收器的地址
-        )
-        // 配置追踪器的资源信息，例如服务名称等
-        .with_trace_config(
-            sdktrace::Config::default().with_resource(Resource::new(vec![KeyValue::new(
-                SERVICE_NAME,
-                "tracing-jaeger", // 设置服务名称为 "tracing-jaeger"
-            )])),
-        )
-        // 使用批量处理器进行追踪数据的导出，`runtime::Tokio` 用于支持异步操作
-        .install_batch(runtime::Tokio)
-}
+    let tracer_provider = exporter::build_tracer_provider(&settings)?;
+    global::set_tracer_provider(tracer_provider.clone());
 
-// 初始化全局追踪器，将 `TracerProvider` 设置为全局
-fn init_tracer() {
-    let tracer_provider = init_tracer_provider().expect("Failed to initialize tracer provider.");
-    global::set_tracer_provider(tracer_provider);
-    let meter_provider = init_meter_provider().expect("Failed to initialize meter provider.");
-    global::set_meter_provider(meter_provider);
+    let meter_provider = exporter::build_meter_provider(&settings)?;
+    global::set_meter_provider(meter_provider.clone());
 
     global::set_text_map_propagator(TraceContextPropagator::new());
+
+    // Registry = EnvFilter（按 RUST_LOG 过滤）+ fmt（控制台输出）+ OpenTelemetryLayer
+    // （把 tracing 的 span/event 桥接到全局 tracer 上），使二者共享同一个"当前上下文"。
+    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    let telemetry_layer =
+        tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("dice_server"));
+
+    tracing_subscriber::registry()
+        .with(env_filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(telemetry_layer)
+        .try_init()?;
+
+    Ok((tracer_provider, meter_provider))
+}
+
+// 等待 Ctrl-C 或（在 Unix 上）SIGTERM，用于触发优雅关闭。
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
 }
 
 // 主函数，启动异步运行时
 #[tokio::main]
-async fn main() -> std::io::Result<()> {
-    init_tracer();
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let (tracer_provider, meter_provider) = init_tracer()?;
+
+    // 关闭 actix-web 自带的信号处理：否则它会在 SIGTERM 时和下面的 shutdown_signal()
+    // 各自触发一次关闭，select! 谁先返回就丢弃另一路，导致在飞请求被提前掐断。
+    let server = HttpServer::new(|| {
+        App::new()
+            .wrap(TracingMiddlewareFactory)
+            .service(randnum)
+            .service(gen_num)
+            .service(ingest::receive_traces)
+    })
+    .disable_signals()
+    .bind(("127.0.0.1", 8080))?
+    .run();
+
+    let server_handle = server.handle();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        tracing::info!("received shutdown signal, flushing telemetry before exit");
+        server_handle.stop(true).await;
+    });
+
+    server.await?;
+
+    // 进程退出前把缓冲的 span/metric 批次刷出去，避免容器被杀时数据静默丢失。
+    if let Err(err) = meter_provider.force_flush() {
+        tracing::warn!(error = %err, "failed to flush metrics");
+    }
+    if let Err(err) = meter_provider.shutdown() {
+        tracing::warn!(error = %err, "failed to shut down meter provider");
+    }
+    for result in tracer_provider.force_flush() {
+        if let Err(err) = result {
+            tracing::warn!(error = %err, "failed to flush spans");
+        }
+    }
+    global::shutdown_tracer_provider();
 
-    HttpServer::new(|| App::new().service(randnum).service(gen_num))
-        .bind(("127.0.0.1", 8080))?
-        .run()
-        .await
+    Ok(())
 }
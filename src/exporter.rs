@@ -0,0 +1,144 @@
+use std::env;
+use std::time::Duration;
+
+use opentelemetry::metrics::MetricsError;
+use opentelemetry::trace::TraceError;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{SpanExporterBuilder, WithExportConfig};
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace as sdktrace;
+use opentelemetry_sdk::{runtime, Resource};
+use opentelemetry_semantic_conventions::resource::SERVICE_NAME;
+
+const DEFAULT_SERVICE_NAME: &str = "tracing-jaeger";
+const DEFAULT_OTLP_GRPC_ENDPOINT: &str = "http://localhost:4317";
+
+/// 导出协议，由 `OTEL_EXPORTER_OTLP_PROTOCOL` 驱动；协议和 endpoint 都未设置时退回到 stdout，方便本地开发。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OtlpProtocol {
+    Grpc,
+    HttpProtobuf,
+}
+
+/// 从环境变量读出的导出器配置，tracer 和 meter 两条流水线共用同一份配置。
+pub struct ExporterSettings {
+    protocol: Option<OtlpProtocol>,
+    endpoint: Option<String>,
+    service_name: String,
+}
+
+impl ExporterSettings {
+    /// 读取 `OTEL_EXPORTER_OTLP_PROTOCOL` / `OTEL_EXPORTER_OTLP_ENDPOINT` / `OTEL_SERVICE_NAME`。
+    /// 不设置 `OTEL_EXPORTER_OTLP_PROTOCOL` 时：若设置了 `OTEL_EXPORTER_OTLP_ENDPOINT` 则走 gRPC，
+    /// 否则使用 stdout 导出器，不需要运行 collector。
+    pub fn from_env() -> Self {
+        let endpoint = env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok();
+
+        let protocol = match env::var("OTEL_EXPORTER_OTLP_PROTOCOL") {
+            Ok(v) if v.eq_ignore_ascii_case("grpc") => Some(OtlpProtocol::Grpc),
+            Ok(v) if v.eq_ignore_ascii_case("http/protobuf") || v.eq_ignore_ascii_case("http") => {
+                Some(OtlpProtocol::HttpProtobuf)
+            }
+            // 没有显式设置协议，但配置了 endpoint：按照 OTel 的约定默认走 gRPC，
+            // 而不是静默地退回 stdout 导出器、把 endpoint 晾在一边。
+            _ if endpoint.is_some() => Some(OtlpProtocol::Grpc),
+            _ => None,
+        };
+
+        ExporterSettings {
+            protocol,
+            endpoint,
+            service_name: env::var("OTEL_SERVICE_NAME")
+                .unwrap_or_else(|_| DEFAULT_SERVICE_NAME.to_string()),
+        }
+    }
+
+    fn resource(&self) -> Resource {
+        Resource::new(vec![KeyValue::new(
+            SERVICE_NAME,
+            self.service_name.clone(),
+        )])
+    }
+}
+
+/// 根据 [`ExporterSettings`] 构建追踪提供者：stdout（默认）、OTLP/gRPC 或 OTLP/HTTP。
+pub fn build_tracer_provider(
+    settings: &ExporterSettings,
+) -> Result<sdktrace::TracerProvider, TraceError> {
+    match settings.protocol {
+        None => {
+            let exporter = opentelemetry_stdout::SpanExporter::default();
+            Ok(sdktrace::TracerProvider::builder()
+                .with_simple_exporter(exporter)
+                .with_config(sdktrace::Config::default().with_resource(settings.resource()))
+                .build())
+        }
+        Some(OtlpProtocol::Grpc) => {
+            let exporter = opentelemetry_otlp::new_exporter().tonic().with_endpoint(
+                settings
+                    .endpoint
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_OTLP_GRPC_ENDPOINT.to_string()),
+            );
+            let exporter = SpanExporterBuilder::from(exporter).build_span_exporter()?;
+            Ok(sdktrace::TracerProvider::builder()
+                .with_batch_exporter(exporter, runtime::Tokio)
+                .with_config(sdktrace::Config::default().with_resource(settings.resource()))
+                .build())
+        }
+        Some(OtlpProtocol::HttpProtobuf) => {
+            let mut exporter = opentelemetry_otlp::new_exporter().http();
+            if let Some(endpoint) = &settings.endpoint {
+                exporter = exporter.with_endpoint(endpoint.clone());
+            }
+            let exporter = SpanExporterBuilder::from(exporter).build_span_exporter()?;
+            Ok(sdktrace::TracerProvider::builder()
+                .with_batch_exporter(exporter, runtime::Tokio)
+                .with_config(sdktrace::Config::default().with_resource(settings.resource()))
+                .build())
+        }
+    }
+}
+
+/// 根据 [`ExporterSettings`] 构建指标提供者：stdout（默认）、OTLP/gRPC 或 OTLP/HTTP。
+pub fn build_meter_provider(
+    settings: &ExporterSettings,
+) -> Result<SdkMeterProvider, MetricsError> {
+    match settings.protocol {
+        None => {
+            let exporter = opentelemetry_stdout::MetricsExporter::default();
+            let reader = PeriodicReader::builder(exporter, runtime::Tokio)
+                .with_interval(Duration::from_secs(5))
+                .build();
+            Ok(SdkMeterProvider::builder()
+                .with_reader(reader)
+                .with_resource(settings.resource())
+                .build())
+        }
+        Some(OtlpProtocol::Grpc) => opentelemetry_otlp::new_pipeline()
+            .metrics(runtime::Tokio)
+            .with_period(Duration::from_secs(5))
+            .with_resource(settings.resource())
+            .with_exporter(
+                opentelemetry_otlp::new_exporter().tonic().with_endpoint(
+                    settings
+                        .endpoint
+                        .clone()
+                        .unwrap_or_else(|| DEFAULT_OTLP_GRPC_ENDPOINT.to_string()),
+                ),
+            )
+            .build(),
+        Some(OtlpProtocol::HttpProtobuf) => {
+            let mut exporter = opentelemetry_otlp::new_exporter().http();
+            if let Some(endpoint) = &settings.endpoint {
+                exporter = exporter.with_endpoint(endpoint.clone());
+            }
+            opentelemetry_otlp::new_pipeline()
+                .metrics(runtime::Tokio)
+                .with_period(Duration::from_secs(5))
+                .with_resource(settings.resource())
+                .with_exporter(exporter)
+                .build()
+        }
+    }
+}
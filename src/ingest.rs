@@ -0,0 +1,140 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use opentelemetry::trace::{Span, Tracer};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use prost::Message;
+
+use crate::attach_parent_context;
+
+/// 从 OTLP `ExportTraceServiceRequest` 解析出的 span，映射到本项目使用的追踪模型：
+/// trace_id/span_id 以十六进制字符串表示，便于日志打印和跨进程关联。
+#[derive(Debug, Clone)]
+struct IngestedSpan {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    name: String,
+    start_time: SystemTime,
+    end_time: SystemTime,
+    attributes: Vec<KeyValue>,
+    events: Vec<IngestedEvent>,
+}
+
+#[derive(Debug, Clone)]
+struct IngestedEvent {
+    name: String,
+    attributes: Vec<KeyValue>,
+}
+
+fn convert_attributes(attrs: &[opentelemetry_proto::tonic::common::v1::KeyValue]) -> Vec<KeyValue> {
+    attrs
+        .iter()
+        .filter_map(|kv| {
+            let value = kv.value.as_ref()?.value.as_ref()?;
+            use opentelemetry_proto::tonic::common::v1::any_value::Value;
+            let rendered = match value {
+                Value::StringValue(s) => s.clone(),
+                Value::BoolValue(b) => b.to_string(),
+                Value::IntValue(i) => i.to_string(),
+                Value::DoubleValue(d) => d.to_string(),
+                other => format!("{other:?}"),
+            };
+            Some(KeyValue::new(kv.key.clone(), rendered))
+        })
+        .collect()
+}
+
+fn decode_resource_spans(request: &ExportTraceServiceRequest) -> Vec<IngestedSpan> {
+    let mut spans = Vec::new();
+
+    for resource_span in &request.resource_spans {
+        for scope_span in &resource_span.scope_spans {
+            for span in &scope_span.spans {
+                let parent_span_id = if span.parent_span_id.is_empty() {
+                    None
+                } else {
+                    Some(hex::encode(&span.parent_span_id))
+                };
+
+                let events = span
+                    .events
+                    .iter()
+                    .map(|event| IngestedEvent {
+                        name: event.name.clone(),
+                        attributes: convert_attributes(&event.attributes),
+                    })
+                    .collect();
+
+                spans.push(IngestedSpan {
+                    trace_id: hex::encode(&span.trace_id),
+                    span_id: hex::encode(&span.span_id),
+                    parent_span_id,
+                    name: span.name.clone(),
+                    start_time: UNIX_EPOCH + Duration::from_nanos(span.start_time_unix_nano),
+                    end_time: UNIX_EPOCH + Duration::from_nanos(span.end_time_unix_nano),
+                    attributes: convert_attributes(&span.attributes),
+                    events,
+                });
+            }
+        }
+    }
+
+    spans
+}
+
+/// 把收到的 span 重新通过本进程已经配置好的导出器流水线发出去，让 dice_server 能够
+/// 充当一个轻量级的 collector/relay，而不必单独起 Jaeger/Collector。
+///
+/// SDK 不允许为新 span 指定自定义的 trace_id/span_id，所以原始 id 和父子关系以
+/// `otel.original_*` 属性的形式保留下来，而不是假装维持了真正的上下文延续。
+fn reexport(spans: &[IngestedSpan]) {
+    let tracer = global::tracer("dice_server.ingest");
+
+    for span in spans {
+        let mut otel_span = tracer
+            .span_builder(span.name.clone())
+            .with_start_time(span.start_time)
+            .start(&tracer);
+
+        otel_span.set_attribute(KeyValue::new("otel.original_trace_id", span.trace_id.clone()));
+        otel_span.set_attribute(KeyValue::new("otel.original_span_id", span.span_id.clone()));
+        if let Some(parent_span_id) = &span.parent_span_id {
+            otel_span.set_attribute(KeyValue::new(
+                "otel.original_parent_span_id",
+                parent_span_id.clone(),
+            ));
+        }
+        for attribute in &span.attributes {
+            otel_span.set_attribute(attribute.clone());
+        }
+        for event in &span.events {
+            otel_span.add_event(event.name.clone(), event.attributes.clone());
+        }
+
+        otel_span.end_with_timestamp(span.end_time);
+    }
+}
+
+/// 接收 OTLP/HTTP protobuf 格式的 `ExportTraceServiceRequest`，解码后转发到内部流水线。
+#[post("/v1/traces")]
+#[tracing::instrument(skip_all)]
+pub async fn receive_traces(req: HttpRequest, body: web::Bytes) -> impl Responder {
+    attach_parent_context(&req);
+
+    let request = match ExportTraceServiceRequest::decode(body.as_ref()) {
+        Ok(request) => request,
+        Err(err) => {
+            tracing::warn!(error = %err, "failed to decode ExportTraceServiceRequest");
+            return HttpResponse::BadRequest().body("invalid OTLP trace payload");
+        }
+    };
+
+    let spans = decode_resource_spans(&request);
+    tracing::info!(span_count = spans.len(), "ingested OTLP trace export request");
+
+    reexport(&spans);
+
+    HttpResponse::Ok().finish()
+}
@@ -0,0 +1,238 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::Instant;
+
+use actix_http::RequestHeadType;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error, HttpMessage};
+use awc::error::SendRequestError;
+use awc::middleware::Transform as AwcTransform;
+use awc::{ConnectRequest, ConnectResponse};
+use opentelemetry::trace::{SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_http::HeaderInjector;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// 从请求头中提取 W3C 追踪上下文（若请求头中没有，则得到一个空的根上下文）。
+fn extract_context(req: &actix_web::HttpRequest) -> Context {
+    global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(req.headers()))
+    })
+}
+
+struct HeaderExtractor<'a>(&'a actix_web::http::header::HeaderMap);
+
+impl<'a> opentelemetry::propagation::Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+/// actix-web 中间件：在每个进入的请求上自动完成追踪上下文的提取、Server span 的创建，
+/// 并在响应完成时关闭该 span。span 会被存入 request extensions，供 handler 取用。
+pub struct TracingMiddlewareFactory;
+
+impl<S, B> Transform<S, ServiceRequest> for TracingMiddlewareFactory
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = TracingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(TracingMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct TracingMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for TracingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let parent_cx = extract_context(req.request());
+
+        let route = req
+            .match_pattern()
+            .unwrap_or_else(|| req.path().to_string());
+        let method = req.method().to_string();
+        let url_full = req.uri().to_string();
+        let metrics_route = route.clone();
+        let start = Instant::now();
+
+        let tracer = global::tracer("dice_server");
+        let span = tracer
+            .span_builder(format!("{method} {route}"))
+            .with_kind(SpanKind::Server)
+            .start_with_context(&tracer, &parent_cx);
+        let cx = Context::current_with_span(span);
+
+        cx.span()
+            .set_attribute(KeyValue::new("http.request.method", method));
+        cx.span().set_attribute(KeyValue::new("http.route", route));
+        cx.span()
+            .set_attribute(KeyValue::new("url.full", url_full));
+
+        req.extensions_mut().insert(cx.clone());
+
+        let service = self.service.clone();
+        Box::pin(async move {
+            let res = service.call(req).await;
+
+            let status = match &res {
+                Ok(response) => {
+                    let status = response.status().as_u16();
+                    cx.span().set_attribute(KeyValue::new(
+                        "http.response.status_code",
+                        status as i64,
+                    ));
+                    if status >= 400 {
+                        cx.span()
+                            .set_status(Status::error(response.status().to_string()));
+                    }
+                    status
+                }
+                Err(err) => {
+                    cx.span().set_status(Status::error(err.to_string()));
+                    err.error_response().status().as_u16()
+                }
+            };
+
+            crate::record_request_metrics(&metrics_route, status, start.elapsed());
+
+            cx.span().end();
+            res
+        })
+    }
+}
+
+/// awc 客户端中间件：为每次外发请求创建 Client span，并把当前上下文注入到请求头中，
+/// 取代 handler 中手写的 `inject_context` 调用。
+///
+/// Client span 以构造时刻的 tracing span（通过 `tracing_opentelemetry` 桥接到 OTel）
+/// 作为父级，而不是 OTel 的线程局部 `Context::current()`——actix 这边从不调用
+/// `Context::attach`，只靠 tracing 的 span 栈维持父子关系，所以必须显式把这个上下文
+/// 带进来，否则发出的 Client span 会变成一个脱节的根 span。
+#[derive(Clone)]
+pub struct ClientTracingMiddleware {
+    parent_cx: Context,
+}
+
+impl ClientTracingMiddleware {
+    pub fn new() -> Self {
+        ClientTracingMiddleware {
+            parent_cx: tracing::Span::current().context(),
+        }
+    }
+}
+
+impl Default for ClientTracingMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> AwcTransform<S, ConnectRequest> for ClientTracingMiddleware
+where
+    S: Service<ConnectRequest, Response = ConnectResponse, Error = SendRequestError> + 'static,
+{
+    type Transform = ClientTracingService<S>;
+
+    fn new_transform(self, service: S) -> Self::Transform {
+        ClientTracingService {
+            service,
+            parent_cx: self.parent_cx,
+        }
+    }
+}
+
+pub struct ClientTracingService<S> {
+    service: S,
+    parent_cx: Context,
+}
+
+impl<S> Service<ConnectRequest> for ClientTracingService<S>
+where
+    S: Service<ConnectRequest, Response = ConnectResponse, Error = SendRequestError> + 'static,
+{
+    type Response = ConnectResponse;
+    type Error = SendRequestError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ConnectRequest) -> Self::Future {
+        let (head_type, body, addr) = match req {
+            ConnectRequest::Client(head_type, body, addr) => (head_type, body, addr),
+            // WebSocket 升级请求没有对应的 HTTP handler span，直接透传。
+            tunnel @ ConnectRequest::Tunnel(..) => {
+                return Box::pin(self.service.call(tunnel));
+            }
+        };
+
+        let mut head = head_type.as_ref().clone();
+
+        let tracer = global::tracer("dice_server");
+        let span = tracer
+            .span_builder(format!("{} {}", head.method, head.uri))
+            .with_kind(SpanKind::Client)
+            .start_with_context(&tracer, &self.parent_cx);
+        let cx = self.parent_cx.with_span(span);
+
+        // `opentelemetry_http::HeaderInjector` 只认 `http` crate 的 HeaderMap，
+        // 而请求头是 actix-http 自己的 HeaderMap，所以两头各转换一次。
+        let mut http_headers: http::HeaderMap = head.headers.clone().into();
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&cx, &mut HeaderInjector(&mut http_headers));
+        });
+        head.headers = http_headers.into();
+
+        let fut = self
+            .service
+            .call(ConnectRequest::Client(RequestHeadType::Owned(head), body, addr));
+
+        Box::pin(async move {
+            let res = fut.await;
+
+            match &res {
+                Ok(ConnectResponse::Client(response)) => {
+                    let status = response.status();
+                    if !status.is_success() {
+                        cx.span().set_status(Status::error(status.to_string()));
+                    }
+                }
+                Ok(ConnectResponse::Tunnel(..)) => {}
+                Err(err) => {
+                    cx.span().set_status(Status::error(err.to_string()));
+                }
+            }
+
+            cx.span().end();
+            res
+        })
+    }
+}